@@ -2,6 +2,7 @@
 extern crate janus_plugin as janus;
 #[macro_use]
 extern crate lazy_static;
+extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
@@ -9,14 +10,34 @@ extern crate serde_json;
 
 mod messages;
 
-use janus::{JanssonValue, Plugin, PluginCallbacks, PluginMetadata, PluginResult, PluginSession,
-            RawJanssonValue, RawPluginResult};
 use janus::session::SessionWrapper;
+use janus::{
+    JanssonValue, Plugin, PluginCallbacks, PluginMetadata, PluginResult, PluginSession,
+    RawJanssonValue, RawPluginResult,
+};
 use messages::Response;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::error::Error;
+use std::ffi::CStr;
 use std::os::raw::{c_char, c_int};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{mpsc, Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Identifies a session within a room, handed to clients so they can address
+/// a specific member when negotiating a mesh of pairwise connections.
+pub type SessionId = u64;
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// How often the keepalive reaper thread wakes up to scan for stale sessions.
+const REAPER_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Session timeout used when the config file doesn't override it.
+const DEFAULT_SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Ring buffer size used when a room enables history without naming its own.
+const DEFAULT_HISTORY_SIZE: usize = 50;
 
 macro_rules! c_str {
     ($lit:expr) => {
@@ -44,41 +65,120 @@ struct RawMessage {
 }
 unsafe impl std::marker::Send for RawMessage {}
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
-pub struct RoomId(u64);
+/// A room identifier. Janus deployments vary in whether they key rooms by a
+/// plain integer or by a human-readable string, so both forms are accepted:
+/// a bare JSON number stays a `Num`, a JSON string stays a `Str`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum RoomId {
+    Num(u64),
+    Str(String),
+}
+
+/// One buffered data-channel message, tagged so a reconnecting or
+/// late-joining client can ask for everything after a given `seq`. `data`
+/// keeps the original bytes (data channels carry binary as often as text)
+/// and is base64-encoded on the wire rather than forced through UTF-8.
+#[derive(Debug, Clone, Serialize)]
+struct HistoryEntry {
+    seq: u64,
+    timestamp_ms: u64,
+    from: SessionId,
+    #[serde(serialize_with = "serialize_as_base64")]
+    data: Vec<u8>,
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn to_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() + 2) / 3 * 4);
+
+    for chunk in bytes.chunks(3) {
+        let b0 = chunk[0];
+        let b1 = chunk.get(1).cloned().unwrap_or(0);
+        let b2 = chunk.get(2).cloned().unwrap_or(0);
+        let n = (u32::from(b0) << 16) | (u32::from(b1) << 8) | u32::from(b2);
+
+        out.push(BASE64_ALPHABET[((n >> 18) & 0x3f) as usize] as char);
+        out.push(BASE64_ALPHABET[((n >> 12) & 0x3f) as usize] as char);
+        out.push(if chunk.len() > 1 {
+            BASE64_ALPHABET[((n >> 6) & 0x3f) as usize] as char
+        } else {
+            '='
+        });
+        out.push(if chunk.len() > 2 {
+            BASE64_ALPHABET[(n & 0x3f) as usize] as char
+        } else {
+            '='
+        });
+    }
+
+    out
+}
+
+fn serialize_as_base64<S>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(&to_base64(data))
+}
+
+/// A room member's standing: the participant who created the room by being
+/// first to join it is the `Publisher`; everyone who joins an already-active
+/// room after them is a `Subscriber`. Every member still negotiates its own
+/// pairwise connection to every other member (full-mesh) regardless of
+/// role — this is descriptive metadata for the admin view, not a signaling
+/// restriction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemberRole {
+    Publisher,
+    Subscriber,
+}
+
+#[derive(Debug)]
+struct Member {
+    session: Weak<Session>,
+    role: MemberRole,
+}
 
 #[derive(Debug)]
 struct Room {
     id: RoomId,
-    caller: Option<Weak<Session>>,
-    callee: Option<Weak<Session>>,
+    members: HashMap<SessionId, Member>,
+    history_capacity: usize,
+    history: Mutex<VecDeque<HistoryEntry>>,
+    next_history_seq: AtomicU64,
 }
 
 impl Room {
-    fn new(id: RoomId) -> Room {
+    fn new(id: RoomId, history_capacity: usize) -> Room {
         Room {
             id,
-            callee: None,
-            caller: None,
+            members: HashMap::new(),
+            history_capacity,
+            history: Mutex::new(VecDeque::new()),
+            next_history_seq: AtomicU64::new(1),
         }
     }
 
-    fn is_new(id: RoomId) -> bool {
+    fn is_new(id: &RoomId) -> bool {
         let rooms = ROOMS.read().unwrap();
-        !rooms.contains_key(&id)
+        !rooms.contains_key(id)
     }
 
     fn is_empty(&self) -> bool {
-        self.caller.is_none() && self.callee.is_none()
+        self.members.is_empty()
     }
 
     fn create(this: Room) {
         let mut rooms = ROOMS.write().unwrap();
-        rooms.insert(this.id, Box::new(this));
+        rooms.insert(this.id.clone(), Box::new(this));
     }
 
-    fn get_mut(rooms: &mut HashMap<RoomId, Box<Room>>, id: RoomId) -> &mut Box<Room> {
-        rooms.get_mut(&id).unwrap()
+    fn get_mut(rooms: &mut HashMap<RoomId, Box<Room>>, id: &RoomId) -> &mut Box<Room> {
+        rooms.get_mut(id).unwrap()
     }
 
     fn all() -> RwLockReadGuard<'static, HashMap<RoomId, Box<Room>>> {
@@ -89,28 +189,78 @@ impl Room {
         ROOMS.write().expect("Cannot lock ROOMS for write")
     }
 
-    fn add_member(&mut self, member: RoomMember) {
-        match member {
-            RoomMember::Callee(ref session) => {
-                self.callee = Some(Arc::downgrade(session));
-            }
-            RoomMember::Caller(ref session) => {
-                self.caller = Some(Arc::downgrade(session));
-            }
+    fn add_member(&mut self, id: SessionId, session: &Arc<Session>, role: MemberRole) {
+        self.members.insert(
+            id,
+            Member {
+                session: Arc::downgrade(session),
+                role,
+            },
+        );
+    }
+
+    /// Every other member's session, for fanning a relayed message out to
+    /// the rest of the mesh.
+    fn other_members(&self, excluding: SessionId) -> Vec<Weak<Session>> {
+        self.members
+            .iter()
+            .filter(|&(&id, _)| id != excluding)
+            .map(|(_, member)| member.session.clone())
+            .collect()
+    }
+
+    /// The role a given member holds in this room, if it's still a member.
+    fn role_of(&self, id: SessionId) -> Option<MemberRole> {
+        self.members.get(&id).map(|member| member.role)
+    }
+
+    /// Appends a data-channel message to the room's ring buffer. A no-op
+    /// when history is disabled (`history_capacity == 0`).
+    fn record_history(&self, from: SessionId, data: Vec<u8>) {
+        if self.history_capacity == 0 {
+            return;
+        }
+
+        let entry = HistoryEntry {
+            seq: self.next_history_seq.fetch_add(1, Ordering::Relaxed),
+            timestamp_ms: unix_millis_now(),
+            from,
+            data,
+        };
+
+        let mut history = self.history.lock().unwrap();
+        if history.len() >= self.history_capacity {
+            history.pop_front();
         }
+        history.push_back(entry);
+    }
+
+    /// Buffered messages with a `seq` strictly greater than `after`, oldest
+    /// first.
+    fn history_since(&self, after: u64) -> Vec<HistoryEntry> {
+        self.history
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| entry.seq > after)
+            .cloned()
+            .collect()
     }
 }
 
-#[derive(Debug)]
-enum RoomMember {
-    Callee(Arc<Session>),
-    Caller(Arc<Session>),
+fn unix_millis_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
 }
 
 #[derive(Debug)]
 pub struct SessionState {
+    id: SessionId,
     room_id: Option<RoomId>,
-    initiator: Option<bool>,
+    last_seen: Instant,
+    departure_notified: bool,
 }
 
 impl SessionState {
@@ -122,18 +272,12 @@ impl SessionState {
     fn get_mut(session: &Session) -> RwLockWriteGuard<SessionState> {
         session.write().expect("Cannot lock session for write")
     }
-
-    fn get_room<'a>(&self, rooms: &'a HashMap<RoomId, Box<Room>>) -> &'a Box<Room> {
-        rooms
-            .get(&self.room_id.expect("Session state has no room id"))
-            .unwrap()
-    }
 }
 
 type Session = SessionWrapper<RwLock<SessionState>>;
 type MessageResult = Result<(), Box<Error>>;
 
-extern "C" fn init(callback: *mut PluginCallbacks, _config_path: *const c_char) -> c_int {
+extern "C" fn init(callback: *mut PluginCallbacks, config_path: *const c_char) -> c_int {
     janus_verb!("--> P2P init");
 
     unsafe {
@@ -155,17 +299,64 @@ extern "C" fn init(callback: *mut PluginCallbacks, _config_path: *const c_char)
         }
     });
 
+    let session_timeout = read_session_timeout(config_path);
+
+    std::thread::spawn(move || {
+        janus_verb!("--> P2P Start keepalive reaper thread");
+
+        loop {
+            std::thread::sleep(REAPER_INTERVAL);
+            reap_stale_sessions(session_timeout);
+        }
+    });
+
     0
 }
 
+/// Reads `session_timeout = <seconds>` out of the plugin's config file, if
+/// one was handed to us, falling back to `DEFAULT_SESSION_TIMEOUT` when the
+/// path is absent, unreadable, or doesn't set the key.
+fn read_session_timeout(config_path: *const c_char) -> Duration {
+    let config_dir = match unsafe { config_path.as_ref() } {
+        Some(_) => unsafe { CStr::from_ptr(config_path) }.to_str().ok(),
+        None => None,
+    };
+
+    let timeout = config_dir
+        .and_then(|dir| std::fs::read_to_string(format!("{}/janus.plugin.p2p.cfg", dir)).ok())
+        .and_then(|contents| {
+            contents
+                .lines()
+                .filter_map(parse_session_timeout_line)
+                .next()
+        });
+
+    match timeout {
+        Some(secs) => Duration::from_secs(secs),
+        None => DEFAULT_SESSION_TIMEOUT,
+    }
+}
+
+fn parse_session_timeout_line(line: &str) -> Option<u64> {
+    let mut parts = line.splitn(2, '=');
+    let key = parts.next()?.trim();
+    if key != "session_timeout" {
+        return None;
+    }
+    let value = parts.next()?.trim().trim_matches(|c| c == '"' || c == ';');
+    value.parse().ok()
+}
+
 extern "C" fn destroy() {
     janus_verb!("--> P2P destroy");
 }
 
 extern "C" fn create_session(handle: *mut PluginSession, error: *mut c_int) {
     let state = SessionState {
+        id: NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed),
         room_id: None,
-        initiator: None,
+        last_seen: Instant::now(),
+        departure_notified: false,
     };
     match unsafe { Session::associate(handle, RwLock::new(state)) } {
         Ok(session) => {
@@ -179,34 +370,86 @@ extern "C" fn create_session(handle: *mut PluginSession, error: *mut c_int) {
     }
 }
 
-extern "C" fn query_session(_handle: *mut PluginSession) -> *mut RawJanssonValue {
+extern "C" fn query_session(handle: *mut PluginSession) -> *mut RawJanssonValue {
     janus_verb!("--> P2P query_session");
-    std::ptr::null_mut()
+
+    let session = match unsafe { Session::from_ptr(handle) } {
+        Ok(session) => session,
+        Err(_) => return std::ptr::null_mut(),
+    };
+
+    let state = SessionState::get(&session);
+    let info = match state.room_id.clone() {
+        Some(room_id) => {
+            let rooms = Room::all();
+            let room = rooms.get(&room_id);
+            let peers: Vec<SessionId> = room
+                .map(|room| {
+                    room.other_members(state.id)
+                        .iter()
+                        .filter_map(Weak::upgrade)
+                        .map(|peer| SessionState::get(&peer).id)
+                        .collect()
+                })
+                .unwrap_or_default();
+            let role = room.and_then(|room| room.role_of(state.id));
+
+            json!({
+                "id": state.id,
+                "room": room_id,
+                "joined": true,
+                "role": role,
+                "peers": peers,
+            })
+        }
+        None => json!({
+            "id": state.id,
+            "joined": false,
+        }),
+    };
+
+    serde_into_jansson(info).into_raw()
 }
 
 extern "C" fn destroy_session(handle: *mut PluginSession, _error: *mut c_int) {
     janus_verb!("--> P2P destroy_session");
 
+    notify_peer_of_departure(handle, "Session destroyed");
+
     let session = unsafe { Session::from_ptr(handle) }.unwrap();
     let state = SessionState::get(&session);
 
-    let mut rooms = Room::all_mut();
-    let room_id = state.room_id.unwrap();
+    SESSIONS
+        .write()
+        .unwrap()
+        .retain(|ref s| s.as_ptr() != handle);
 
-    let is_empty = {
-        let room = Room::get_mut(&mut rooms, room_id);
+    leave_room(state.room_id.clone(), state.id);
+}
 
-        SESSIONS
-            .write()
-            .unwrap()
-            .retain(|ref s| s.as_ptr() != handle);
+/// Clears a session's slot out of its room, removing the room itself once it
+/// has no members left. Shared by `destroy_session` and the keepalive
+/// reaper, which both stop tracking a session outside of the normal
+/// `"hangup"` message flow. A session with no room (e.g. one the reaper
+/// already tore down, which clears its `room_id`, before Janus core calls
+/// `destroy_session` on the same handle) and a room that is no longer in
+/// `ROOMS` are both no-ops rather than a panic.
+fn leave_room(room_id: Option<RoomId>, session_id: SessionId) {
+    let room_id = match room_id {
+        Some(room_id) => room_id,
+        None => return,
+    };
 
-        match state.initiator {
-            Some(true) => room.caller = None,
-            Some(false) | None => room.callee = None,
+    let mut rooms = Room::all_mut();
+    let is_empty = match rooms.get_mut(&room_id) {
+        Some(room) => {
+            room.members.remove(&session_id);
+            room.is_empty()
+        }
+        None => {
+            janus_verb!("Room #{:?} is already gone, nothing to leave.", room_id);
+            return;
         }
-
-        room.is_empty()
     };
 
     if is_empty {
@@ -219,6 +462,38 @@ extern "C" fn destroy_session(handle: *mut PluginSession, _error: *mut c_int) {
     }
 }
 
+/// Scans live sessions for ones that haven't sent any message (a signaling
+/// message, a `"keepalive"`, or a data-channel packet) in too long, and
+/// tears them down as if their transport had disappeared: the remaining
+/// peer gets a synthetic hangup, the session is dropped from `SESSIONS`,
+/// and its room slot is vacated.
+fn reap_stale_sessions(timeout: Duration) {
+    let stale: Vec<*mut PluginSession> = SESSIONS
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|session| SessionState::get(session).last_seen.elapsed() > timeout)
+        .map(|session| session.as_ptr())
+        .collect();
+
+    for handle in stale {
+        janus_verb!("Session {:?} timed out, reaping it.", handle);
+        notify_peer_of_departure(handle, "Session timed out");
+
+        let session = match unsafe { Session::from_ptr(handle) } {
+            Ok(session) => session,
+            Err(_) => continue,
+        };
+        let (room_id, session_id) = {
+            let mut state = SessionState::get_mut(&session);
+            (state.room_id.take(), state.id)
+        };
+
+        SESSIONS.write().unwrap().retain(|s| s.as_ptr() != handle);
+        leave_room(room_id, session_id);
+    }
+}
+
 extern "C" fn handle_message(
     handle: *mut PluginSession,
     transaction: *mut c_char,
@@ -229,32 +504,60 @@ extern "C" fn handle_message(
 
     let result = match unsafe { Session::from_ptr(handle) } {
         Ok(ref session) => {
-            let message = RawMessage {
-                session: Arc::downgrade(session),
-                transaction: transaction,
-                message: unsafe { JanssonValue::new(message) },
-                jsep: unsafe { JanssonValue::new(jsep) },
-            };
-
-            let mutex = CHANNEL.lock().unwrap();
-            let tx = mutex.as_ref().unwrap();
-
-            janus_verb!("--> P2P sending message to channel");
-            tx.send(message).expect("Sending to channel has failed");
-
-            PluginResult::ok_wait(None)
+            let parsed = unsafe { JanssonValue::new(message) };
+
+            match parsed {
+                Some(parsed) => {
+                    SessionState::get_mut(session).last_seen = Instant::now();
+
+                    if is_keepalive(&parsed) {
+                        PluginResult::ok(serde_into_jansson(json!({ "janus": "ack" })))
+                    } else {
+                        let message = RawMessage {
+                            session: Arc::downgrade(session),
+                            transaction: transaction,
+                            message: Some(parsed),
+                            jsep: unsafe { JanssonValue::new(jsep) },
+                        };
+
+                        let mutex = CHANNEL.lock().unwrap();
+                        let tx = mutex.as_ref().unwrap();
+
+                        janus_verb!("--> P2P sending message to channel");
+                        tx.send(message).expect("Sending to channel has failed");
+
+                        PluginResult::ok_wait(None)
+                    }
+                }
+                None => PluginResult::error(c_str!("No message provided")),
+            }
         }
         Err(_) => PluginResult::error(c_str!("No handle associated with session")),
     };
     result.into_raw()
 }
 
+/// Cheap, schema-free peek at whether an incoming message is a `"keepalive"`,
+/// so `handle_message` can ack it inline instead of round-tripping it
+/// through the async processing channel.
+fn is_keepalive(message: &JanssonValue) -> bool {
+    #[derive(Deserialize)]
+    struct Envelope {
+        janus: String,
+    }
+
+    serde_from_jansson::<Envelope>(message)
+        .map(|envelope| envelope.janus == "keepalive")
+        .unwrap_or(false)
+}
+
 extern "C" fn setup_media(_handle: *mut PluginSession) {
     janus_verb!("--> P2P setup_media");
 }
 
-extern "C" fn hangup_media(_handle: *mut PluginSession) {
+extern "C" fn hangup_media(handle: *mut PluginSession) {
     janus_verb!("--> P2P hangup_media");
+    notify_peer_of_departure(handle, "Close PC");
 }
 
 extern "C" fn incoming_rtp(
@@ -273,7 +576,51 @@ extern "C" fn incoming_rtcp(
 ) {
 }
 
-extern "C" fn incoming_data(_handle: *mut PluginSession, _buf: *mut c_char, _len: c_int) {}
+extern "C" fn incoming_data(handle: *mut PluginSession, buf: *mut c_char, len: c_int) {
+    if buf.is_null() || len <= 0 {
+        return;
+    }
+
+    let data = unsafe { std::slice::from_raw_parts(buf as *const u8, len as usize) }.to_vec();
+
+    let session = match unsafe { Session::from_ptr(handle) } {
+        Ok(session) => session,
+        Err(_) => return,
+    };
+
+    let (room_id, session_id) = {
+        let mut state = SessionState::get_mut(&session);
+        state.last_seen = Instant::now();
+        let room_id = match state.room_id.clone() {
+            Some(room_id) => room_id,
+            None => return,
+        };
+        (room_id, state.id)
+    };
+
+    let peers = {
+        let rooms = Room::all();
+        match rooms.get(&room_id) {
+            Some(room) => {
+                room.record_history(session_id, data.clone());
+                room.other_members(session_id)
+            }
+            None => return,
+        }
+    };
+
+    let relay_data_fn = acquire_gateway().relay_data;
+    for peer in peers {
+        if let Some(peer) = peer.upgrade() {
+            let mut buf = data.clone();
+            relay_data_fn(
+                peer.handle,
+                buf.as_mut_ptr() as *mut c_char,
+                buf.len() as c_int,
+            );
+        }
+    }
+}
 
 extern "C" fn slow_link(_handle: *mut PluginSession, _uplink: c_int, _video: c_int) {}
 
@@ -293,19 +640,28 @@ fn handle_message_async(msg: RawMessage) -> MessageResult {
             Ok(resp) => {
                 println!("--> Got response: {:?}", resp);
                 match resp {
-                    Response::Join { peer, mut payload }
-                    | Response::Call { peer, mut payload }
-                    | Response::Accept { peer, mut payload }
-                    | Response::Candidate { peer, mut payload } => match peer.upgrade() {
-                        Some(peer) => {
-                            {
-                                let json_obj = payload.as_object_mut().unwrap();
-                                json_obj.entry("ok").or_insert(json!(true));
+                    Response::Join { peers, mut payload }
+                    | Response::Call { peers, mut payload }
+                    | Response::Accept { peers, mut payload }
+                    | Response::Candidate { peers, mut payload }
+                    | Response::Hangup { peers, mut payload }
+                    | Response::History { peers, mut payload } => {
+                        {
+                            let json_obj = payload.as_object_mut().unwrap();
+                            json_obj.entry("ok").or_insert(json!(true));
+                        }
+
+                        for peer in peers {
+                            match peer.upgrade() {
+                                Some(peer) => {
+                                    push_response(&peer, transaction, payload.clone())?;
+                                }
+                                None => janus_warn!("Peer has gone, skipping relay to it."),
                             }
-                            push_response(&peer, transaction, payload)
                         }
-                        None => Err(messages::Error::PeerHasGone)?,
-                    },
+
+                        Ok(())
+                    }
                 }
             }
             Err(err) => {
@@ -322,6 +678,52 @@ fn handle_message_async(msg: RawMessage) -> MessageResult {
     }
 }
 
+/// Pushes a synthetic `"hangup"` event to the other side of the room a
+/// session belongs to, if any. Used when a session disappears without
+/// sending an explicit `"hangup"` message itself, so the remaining peer
+/// doesn't have to wait for its own ICE connection to time out.
+///
+/// Janus runs `hangup_media` and `destroy_session` back to back during
+/// normal teardown, and the reaper may also tear a session down itself, so
+/// this only fires once per session: the first caller flips
+/// `departure_notified` and every later one is a no-op.
+fn notify_peer_of_departure(handle: *mut PluginSession, reason: &str) {
+    let session = match unsafe { Session::from_ptr(handle) } {
+        Ok(session) => session,
+        Err(_) => return,
+    };
+
+    let (room_id, session_id) = {
+        let mut state = SessionState::get_mut(&session);
+        if state.departure_notified {
+            return;
+        }
+        state.departure_notified = true;
+        (state.room_id.clone(), state.id)
+    };
+    let room_id = match room_id {
+        Some(room_id) => room_id,
+        None => return,
+    };
+
+    let peers = {
+        let rooms = Room::all();
+        match rooms.get(&room_id) {
+            Some(room) => room.other_members(session_id),
+            None => return,
+        }
+    };
+
+    let payload = messages::hangup_event(session_id, Some(reason));
+    for peer in peers {
+        if let Some(peer) = peer.upgrade() {
+            if let Err(e) = push_response(&peer, std::ptr::null_mut(), payload.clone()) {
+                janus_err!("Failed to notify peer about hangup: {}", e);
+            }
+        }
+    }
+}
+
 fn push_response(
     peer: &Session,
     transaction: *mut c_char,
@@ -344,6 +746,17 @@ fn serde_into_jansson(value: serde_json::Value) -> JanssonValue {
     JanssonValue::from_str(&value.to_string(), janus::JanssonDecodingFlags::empty()).unwrap()
 }
 
+fn serde_from_jansson<T>(value: &JanssonValue) -> Result<T, serde_json::Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let json_str = value
+        .to_libcstring(janus::JanssonEncodingFlags::empty())
+        .to_str()
+        .expect("Jansson value is not valid UTF-8");
+    serde_json::from_str(json_str)
+}
+
 fn acquire_gateway() -> &'static PluginCallbacks {
     unsafe { GATEWAY }.expect("Gateway is NONE")
 }
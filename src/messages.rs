@@ -0,0 +1,286 @@
+use super::{
+    serde_from_jansson, MemberRole, Room, RoomId, Session, SessionId, SessionState,
+    DEFAULT_HISTORY_SIZE,
+};
+use janus::JanssonValue;
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::{Arc, Weak};
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "janus", rename_all = "lowercase")]
+enum IncomingMessage {
+    Join {
+        room: RoomId,
+        #[serde(default)]
+        history: bool,
+        #[serde(default)]
+        history_size: Option<usize>,
+    },
+    Call {
+        to: SessionId,
+        jsep: serde_json::Value,
+    },
+    Accept {
+        to: SessionId,
+        jsep: serde_json::Value,
+    },
+    Candidate {
+        to: SessionId,
+        candidate: serde_json::Value,
+    },
+    Hangup {
+        reason: Option<String>,
+    },
+    History {
+        after: u64,
+    },
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidJson(serde_json::Error),
+    NotInRoom,
+    AlreadyInRoom,
+    PeerNotFound,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl StdError for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::InvalidJson(_) => "invalid json",
+            Error::NotInRoom => "session is not in a room",
+            Error::AlreadyInRoom => "session is already in a room",
+            Error::PeerNotFound => "target peer is not in the room",
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error::InvalidJson(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum Response {
+    Join {
+        peers: Vec<Weak<Session>>,
+        payload: serde_json::Value,
+    },
+    Call {
+        peers: Vec<Weak<Session>>,
+        payload: serde_json::Value,
+    },
+    Accept {
+        peers: Vec<Weak<Session>>,
+        payload: serde_json::Value,
+    },
+    Candidate {
+        peers: Vec<Weak<Session>>,
+        payload: serde_json::Value,
+    },
+    Hangup {
+        peers: Vec<Weak<Session>>,
+        payload: serde_json::Value,
+    },
+    History {
+        peers: Vec<Weak<Session>>,
+        payload: serde_json::Value,
+    },
+}
+
+pub fn process(session: &Arc<Session>, message: JanssonValue) -> Result<Response, Error> {
+    let incoming: IncomingMessage = serde_from_jansson(&message)?;
+
+    match incoming {
+        IncomingMessage::Join {
+            room,
+            history,
+            history_size,
+        } => handle_join(session, room, history, history_size),
+        IncomingMessage::Call { to, jsep } => handle_call(session, to, jsep),
+        IncomingMessage::Accept { to, jsep } => handle_accept(session, to, jsep),
+        IncomingMessage::Candidate { to, candidate } => handle_candidate(session, to, candidate),
+        IncomingMessage::Hangup { reason } => handle_hangup(session, reason),
+        IncomingMessage::History { after } => handle_history(session, after),
+    }
+}
+
+/// Adds the session to the room (creating it if this is the first member,
+/// in which case it's recorded as the room's `Publisher` and everyone after
+/// it a `Subscriber`) and hands back the ids of the members already there,
+/// so the newcomer can initiate offers to each of them and assemble a full
+/// mesh. `history` and `history_size` only take effect when they create the
+/// room; joining one that already exists keeps its original buffering
+/// options.
+fn handle_join(
+    session: &Arc<Session>,
+    room_id: RoomId,
+    history: bool,
+    history_size: Option<usize>,
+) -> Result<Response, Error> {
+    {
+        let state = SessionState::get(session);
+        if state.room_id.is_some() {
+            return Err(Error::AlreadyInRoom);
+        }
+    }
+
+    let is_new_room = Room::is_new(&room_id);
+    let role = if is_new_room {
+        MemberRole::Publisher
+    } else {
+        MemberRole::Subscriber
+    };
+
+    if is_new_room {
+        let history_capacity = if history {
+            history_size.unwrap_or(DEFAULT_HISTORY_SIZE)
+        } else {
+            0
+        };
+        Room::create(Room::new(room_id.clone(), history_capacity));
+    }
+
+    let session_id = SessionState::get(session).id;
+
+    let participants: Vec<SessionId> = {
+        let mut rooms = Room::all_mut();
+        let room = Room::get_mut(&mut rooms, &room_id);
+        let participants = room.members.keys().cloned().collect();
+        room.add_member(session_id, session, role);
+        participants
+    };
+
+    {
+        let mut state = SessionState::get_mut(session);
+        state.room_id = Some(room_id.clone());
+    }
+
+    Ok(Response::Join {
+        peers: vec![Arc::downgrade(session)],
+        payload: json!({
+            "event": "joined",
+            "room": room_id,
+            "id": session_id,
+            "participants": participants,
+        }),
+    })
+}
+
+fn handle_call(
+    session: &Arc<Session>,
+    to: SessionId,
+    jsep: serde_json::Value,
+) -> Result<Response, Error> {
+    let (from, peer) = member(session, to)?;
+
+    Ok(Response::Call {
+        peers: vec![peer],
+        payload: json!({ "event": "call", "from": from, "to": to, "jsep": jsep }),
+    })
+}
+
+fn handle_accept(
+    session: &Arc<Session>,
+    to: SessionId,
+    jsep: serde_json::Value,
+) -> Result<Response, Error> {
+    let (from, peer) = member(session, to)?;
+
+    Ok(Response::Accept {
+        peers: vec![peer],
+        payload: json!({ "event": "accept", "from": from, "to": to, "jsep": jsep }),
+    })
+}
+
+fn handle_candidate(
+    session: &Arc<Session>,
+    to: SessionId,
+    candidate: serde_json::Value,
+) -> Result<Response, Error> {
+    let (from, peer) = member(session, to)?;
+
+    Ok(Response::Candidate {
+        peers: vec![peer],
+        payload: json!({ "event": "candidate", "from": from, "to": to, "candidate": candidate }),
+    })
+}
+
+fn handle_hangup(session: &Arc<Session>, reason: Option<String>) -> Result<Response, Error> {
+    let (from, peers) = other_members(session)?;
+
+    // An explicit hangup already notifies the room; mark the session so the
+    // teardown that follows (hangup_media/destroy_session) doesn't also fire
+    // a synthetic one.
+    SessionState::get_mut(session).departure_notified = true;
+
+    Ok(Response::Hangup {
+        peers,
+        payload: hangup_event(from, reason.as_ref().map(String::as_str)),
+    })
+}
+
+/// Replays buffered data-channel messages posted after `after`, letting a
+/// reconnecting or late-joining client catch up without having witnessed
+/// them live.
+fn handle_history(session: &Arc<Session>, after: u64) -> Result<Response, Error> {
+    let state = SessionState::get(session);
+    let room_id = state.room_id.as_ref().ok_or(Error::NotInRoom)?;
+
+    let rooms = Room::all();
+    let room = rooms.get(room_id).ok_or(Error::NotInRoom)?;
+    let messages = room.history_since(after);
+
+    Ok(Response::History {
+        peers: vec![Arc::downgrade(session)],
+        payload: json!({ "event": "history", "messages": messages }),
+    })
+}
+
+/// Builds the `"hangup"` event pushed to a room's remaining members, whether
+/// the hangup was requested explicitly or synthesized because the session
+/// disappeared without a clean teardown.
+pub fn hangup_event(from: SessionId, reason: Option<&str>) -> serde_json::Value {
+    json!({ "event": "hangup", "from": from, "reason": reason })
+}
+
+/// Resolves the caller's own id and every other member of its room, for
+/// fanning a hangup out across the mesh.
+fn other_members(session: &Arc<Session>) -> Result<(SessionId, Vec<Weak<Session>>), Error> {
+    let state = SessionState::get(session);
+    let room_id = state.room_id.as_ref().ok_or(Error::NotInRoom)?;
+    let session_id = state.id;
+
+    let rooms = Room::all();
+    let room = rooms.get(room_id).ok_or(Error::NotInRoom)?;
+
+    Ok((session_id, room.other_members(session_id)))
+}
+
+/// Resolves the caller's own id and a single named member of its room, so an
+/// offer/answer/candidate can be addressed to the specific peer it
+/// negotiates a pairwise connection with, instead of broadcast to the whole
+/// mesh.
+fn member(session: &Arc<Session>, target: SessionId) -> Result<(SessionId, Weak<Session>), Error> {
+    let state = SessionState::get(session);
+    let room_id = state.room_id.as_ref().ok_or(Error::NotInRoom)?;
+    let session_id = state.id;
+
+    let rooms = Room::all();
+    let room = rooms.get(room_id).ok_or(Error::NotInRoom)?;
+    let peer = room
+        .members
+        .get(&target)
+        .map(|member| member.session.clone())
+        .ok_or(Error::PeerNotFound)?;
+
+    Ok((session_id, peer))
+}